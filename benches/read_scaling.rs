@@ -0,0 +1,39 @@
+//! Demonstrates that `get_price` scales across cores now that `ThreadSafe`
+//! is backed by an `RwLock`: concurrent readers no longer serialise.
+
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use priceholder::{PriceHolder, ThreadSafe};
+
+fn concurrent_reads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_price_scaling");
+
+    for threads in [1usize, 2, 4, 8] {
+        let mut ph = ThreadSafe::new();
+        ph.put_price("AAPL".to_string(), 150u64).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &n| {
+            b.iter(|| {
+                let handles: Vec<_> = (0..n)
+                    .map(|_| {
+                        let ph = ph.clone();
+                        thread::spawn(move || {
+                            for _ in 0..1_000 {
+                                let _ = ph.get_price("AAPL".to_string());
+                            }
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_reads);
+criterion_main!(benches);
@@ -0,0 +1,151 @@
+//! An async/await [`AsyncPriceHolder`] backed by non-blocking channels.
+//!
+//! Where the blocking [`crate::PriceHolder`] parks an OS thread on
+//! [`std::sync::mpsc::Receiver::recv`], an awaiter here suspends on a
+//! [`tokio::sync::broadcast::Receiver`], so thousands of symbols can be
+//! watched from a single runtime. `put_price` stays synchronous and cheap: it
+//! just sends the new value to the stored async senders.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+
+/// The async counterpart of [`crate::PriceHolder`].
+///
+/// `put_price` and `get_price` remain synchronous; only `next_price` awaits.
+#[allow(async_fn_in_trait)]
+pub trait AsyncPriceHolder<T> {
+    fn put_price(&self, symbol: String, value: T);
+    fn get_price(&self, symbol: String) -> Option<T>;
+    async fn next_price(&self, symbol: String) -> Result<T, RecvError>;
+}
+
+/// A thread-safe, `Clone`able holder whose waiters suspend rather than block.
+#[derive(Clone)]
+pub struct TokioThreadSafe<T> {
+    inner: Arc<Mutex<HashMap<String, Price<T>>>>,
+}
+
+impl<T> TokioThreadSafe<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<T> Default for TokioThreadSafe<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AsyncPriceHolder<T> for TokioThreadSafe<T>
+where
+    T: Copy + Send,
+{
+    fn put_price(&self, symbol: String, value: T) {
+        self.inner
+            .lock()
+            .unwrap()
+            .entry(symbol)
+            .or_insert_with(Price::new)
+            .update_price(value);
+    }
+
+    fn get_price(&self, symbol: String) -> Option<T> {
+        self.inner.lock().unwrap().get(&symbol).and_then(|p| p.value)
+    }
+
+    async fn next_price(&self, symbol: String) -> Result<T, RecvError> {
+        let mut rx = {
+            self.inner
+                .lock()
+                .unwrap()
+                .entry(symbol)
+                .or_insert_with(Price::new)
+                .subscribe()
+        }; // unlock mutex before awaiting
+        loop {
+            // A burst between `subscribe()` and the first poll can make the
+            // receiver lag; skip past it rather than surfacing a spurious
+            // failure for a method whose contract is "the next price".
+            match rx.recv().await {
+                Err(RecvError::Lagged(_)) => continue,
+                other => return other,
+            }
+        }
+    }
+}
+
+struct Price<T> {
+    value: Option<T>,
+    tx: Sender<T>,
+}
+
+impl<T> Price<T>
+where
+    T: Copy + Send,
+{
+    fn new() -> Self {
+        // A small buffer keeps `put_price` lag-free for momentarily slow awaiters.
+        let (tx, _rx) = broadcast::channel(16);
+        Self { value: None, tx }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<T> {
+        self.tx.subscribe()
+    }
+
+    fn update_price(&mut self, value: T) {
+        self.value = Some(value);
+        // Errors only when no awaiter is currently subscribed; that is fine.
+        let _ = self.tx.send(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn next_price_wakes_on_the_next_put() {
+        let ph = TokioThreadSafe::new();
+
+        ph.put_price("symbol".to_string(), 1u64);
+
+        {
+            let ph = ph.clone();
+            tokio::spawn(async move {
+                sleep(Duration::from_millis(100)).await;
+                ph.put_price("symbol".to_string(), 2);
+            });
+        }
+
+        let price = ph.next_price("symbol".to_string()).await.unwrap();
+        assert_eq!(price, 2);
+    }
+
+    #[tokio::test]
+    async fn multiple_awaiters_receive_the_same_tick() {
+        let ph = TokioThreadSafe::new();
+
+        let mut handles = vec![];
+        for _ in 0..4 {
+            let ph = ph.clone();
+            handles.push(tokio::spawn(async move {
+                ph.next_price("symbol".to_string()).await.unwrap()
+            }));
+        }
+
+        sleep(Duration::from_millis(100)).await;
+        ph.put_price("symbol".to_string(), 7u64);
+
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), 7);
+        }
+    }
+}
@@ -1,42 +1,183 @@
-use std::collections::HashMap;
-use std::sync::mpsc::{sync_channel, Receiver, RecvError, SendError, SyncSender};
-use std::sync::{Arc, Mutex};
+//! A price holder indexed by symbol.
+//!
+//! The blocking [`PriceHolder`] types live behind the default `sync` feature.
+//! Enabling the `async` feature additionally exposes [`asynchronous`], an
+//! async/await flavour that suspends an awaiter instead of parking an OS
+//! thread on [`std::sync::mpsc::Receiver::recv`].
+
+#[cfg(feature = "sync")]
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "sync")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "sync")]
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvError, SendError, Sender, SyncSender};
+#[cfg(feature = "sync")]
+use std::sync::{Arc, RwLock, Weak};
+#[cfg(feature = "sync")]
+use std::str::FromStr;
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+
+/// Error returned when a raw market-data string cannot be parsed into the
+/// value type.
+#[cfg(feature = "sync")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError(pub String);
+
+/// A pluggable parser turning a textual feed value into the stored type,
+/// selected at construction (see [`ThreadUnsafe::with_converter`] and
+/// [`ThreadUnsafe::parsing`]).
+#[cfg(feature = "sync")]
+pub type Converter<T> = Arc<dyn Fn(&str) -> Result<T, ConversionError> + Send + Sync>;
+
+/// Error returned by [`PriceHolder::put_price_str`]: the raw value failed to
+/// parse, or the parsed value could not be delivered to a waiter.
+#[cfg(feature = "sync")]
+pub enum IngestError<T> {
+    Parse(ConversionError),
+    Send(SendError<T>),
+}
 
-use num::Unsigned;
+#[cfg(feature = "sync")]
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not parse price from {:?}", self.0)
+    }
+}
 
+#[cfg(feature = "sync")]
+impl std::error::Error for ConversionError {}
+
+#[cfg(feature = "sync")]
+impl<T> std::fmt::Debug for IngestError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::Parse(e) => write!(f, "Parse({e:?})"),
+            IngestError::Send(e) => write!(f, "Send({e:?})"),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> std::fmt::Display for IngestError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::Parse(e) => write!(f, "{e}"),
+            IngestError::Send(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: Send> std::error::Error for IngestError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IngestError::Parse(e) => Some(e),
+            IngestError::Send(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
 pub trait PriceHolder<T> {
     fn put_price(&mut self, symbol: String, value: T) -> Result<(), SendError<T>>;
     fn get_price(&self, symbol: String) -> Option<T>;
     fn next_price(&mut self, symbol: String) -> Result<T, RecvError>;
+    /// Like [`next_price`](PriceHolder::next_price), but only wakes when the
+    /// next value satisfies `pred` (e.g. crosses a threshold). Non-matching
+    /// updates are ignored and the waiter is retained.
+    fn next_price_where(
+        &mut self,
+        symbol: String,
+        pred: impl Fn(T) -> bool + Send + Sync + 'static,
+    ) -> Result<T, RecvError>;
+    /// Register a long-lived listener that receives *every* subsequent
+    /// [`put_price`](PriceHolder::put_price) value for `symbol` until the
+    /// returned [`Receiver`] is dropped.
+    fn subscribe(&mut self, symbol: String) -> Receiver<T>;
+    /// The retained tick history for `symbol`, oldest first. Empty unless the
+    /// holder was built with [`ThreadUnsafe::with_history`].
+    fn history(&self, symbol: String) -> Vec<(Instant, T)>;
+    /// The most recent `n` ticks for `symbol`, oldest first.
+    fn last_n(&self, symbol: String, n: usize) -> Vec<(Instant, T)>;
+    /// The most recent tick that is at least `age` old, if any.
+    fn get_price_at(&self, symbol: String, age: Duration) -> Option<(Instant, T)>;
+    /// Parse `raw` with the configured [`Converter`] and store it, letting a
+    /// textual feed be ingested without the caller pre-parsing.
+    fn put_price_str(&mut self, symbol: String, raw: &str) -> Result<(), IngestError<T>>;
 }
 
+#[cfg(feature = "sync")]
 pub struct ThreadUnsafe<T> {
     hashmap: HashMap<String, Price<T>>,
+    history_capacity: usize,
+    converter: Option<Converter<T>>,
 }
 
+#[cfg(feature = "sync")]
 impl<T> ThreadUnsafe<T> {
     pub fn new() -> Self {
         Self {
             hashmap: HashMap::new(),
+            history_capacity: 0,
+            converter: None,
+        }
+    }
+
+    /// Build a holder that retains up to `n` recent ticks per symbol. With the
+    /// default capacity of `0`, no history is recorded and the update path
+    /// incurs no extra work.
+    pub fn with_history(n: usize) -> Self {
+        Self {
+            history_capacity: n,
+            ..Self::new()
         }
     }
+
+    /// Attach a parser for [`put_price_str`](PriceHolder::put_price_str). Use
+    /// this for fixed-point or otherwise custom decimal decoding; for types
+    /// that already implement [`FromStr`] prefer [`parsing`](Self::parsing).
+    pub fn with_converter<F>(mut self, converter: F) -> Self
+    where
+        F: Fn(&str) -> Result<T, ConversionError> + Send + Sync + 'static,
+    {
+        self.converter = Some(Arc::new(converter));
+        self
+    }
 }
 
+#[cfg(feature = "sync")]
+impl<T> ThreadUnsafe<T>
+where
+    T: FromStr + 'static,
+{
+    /// Build a holder that parses raw feed values via `T`'s own [`FromStr`],
+    /// which covers integer, float, and decimal (e.g. `rust_decimal::Decimal`)
+    /// value types.
+    pub fn parsing() -> Self {
+        Self::new().with_converter(|raw| raw.parse().map_err(|_| ConversionError(raw.to_string())))
+    }
+}
+
+#[cfg(feature = "sync")]
 impl<T> Default for ThreadUnsafe<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "sync")]
 impl<T> PriceHolder<T> for ThreadUnsafe<T>
 where
-    T: Unsigned + Copy,
+    T: Copy + Send,
 {
     fn put_price(&mut self, symbol: String, value: T) -> Result<(), SendError<T>> {
         match self.hashmap.get_mut(&symbol) {
             Some(price) => price.update_price(value),
             None => {
-                self.hashmap.insert(symbol, Price::from(value));
+                self.hashmap
+                    .insert(symbol, Price::from(value, self.history_capacity));
                 Ok(())
             }
         }
@@ -50,111 +191,310 @@ where
     }
 
     fn next_price(&mut self, symbol: String) -> Result<T, RecvError> {
-        self.price_receiver(symbol).recv()
+        self.next_price_where(symbol, |_| true)
+    }
+
+    fn next_price_where(
+        &mut self,
+        symbol: String,
+        pred: impl Fn(T) -> bool + Send + Sync + 'static,
+    ) -> Result<T, RecvError> {
+        self.price_receiver(symbol, pred).recv()
+    }
+
+    fn subscribe(&mut self, symbol: String) -> Receiver<T> {
+        let (tx, rx) = channel();
+        match self.hashmap.get_mut(&symbol) {
+            Some(price) => price.add_subscriber(tx),
+            None => {
+                let mut p = Price::new(self.history_capacity);
+                p.add_subscriber(tx);
+                self.hashmap.insert(symbol, p);
+            }
+        }
+        rx
+    }
+
+    fn history(&self, symbol: String) -> Vec<(Instant, T)> {
+        match self.hashmap.get(&symbol) {
+            Some(price) => price.history.iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn last_n(&self, symbol: String, n: usize) -> Vec<(Instant, T)> {
+        match self.hashmap.get(&symbol) {
+            Some(price) => {
+                let skip = price.history.len().saturating_sub(n);
+                price.history.iter().skip(skip).copied().collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn get_price_at(&self, symbol: String, age: Duration) -> Option<(Instant, T)> {
+        self.hashmap.get(&symbol).and_then(|price| {
+            price
+                .history
+                .iter()
+                .rev()
+                .find(|(instant, _)| instant.elapsed() >= age)
+                .copied()
+        })
+    }
+
+    fn put_price_str(&mut self, symbol: String, raw: &str) -> Result<(), IngestError<T>> {
+        let converter = self
+            .converter
+            .clone()
+            .ok_or_else(|| IngestError::Parse(ConversionError("no converter configured".into())))?;
+        let value = converter(raw).map_err(IngestError::Parse)?;
+        self.put_price(symbol, value).map_err(IngestError::Send)
     }
 }
 
+#[cfg(feature = "sync")]
 impl<T> ThreadUnsafe<T>
 where
-    T: Unsigned + Copy,
+    T: Copy + Send,
 {
-    fn price_receiver(&mut self, symbol: String) -> Receiver<T> {
+    fn price_receiver(
+        &mut self,
+        symbol: String,
+        pred: impl Fn(T) -> bool + Send + Sync + 'static,
+    ) -> WaiterReceiver<T> {
         let (tx, rx) = sync_channel(1);
+        // A liveness token whose `Weak` lets `notify_waiters` prune this waiter
+        // once the receiver is dropped, even if its predicate never matches.
+        let alive = Arc::new(());
+        let probe = Arc::downgrade(&alive);
         match self.hashmap.get_mut(&symbol) {
-            Some(price) => price.add_waiter(tx),
+            Some(price) => price.add_waiter(tx, pred, probe),
             None => {
-                let mut p = Price::new();
-                p.add_waiter(tx);
+                let mut p = Price::new(self.history_capacity);
+                p.add_waiter(tx, pred, probe);
                 self.hashmap.insert(symbol, p);
             }
         }
-        rx
+        WaiterReceiver { rx, _alive: alive }
     }
 }
 
+/// A blocking receiver for a pending waiter. Holding it keeps the waiter
+/// registered; dropping it lets the holder prune the waiter on the next update.
+#[cfg(feature = "sync")]
+struct WaiterReceiver<T> {
+    rx: Receiver<T>,
+    _alive: Arc<()>,
+}
+
+#[cfg(feature = "sync")]
+impl<T> WaiterReceiver<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        self.rx.recv()
+    }
+}
+
+#[cfg(feature = "sync")]
 #[derive(Clone)]
 pub struct ThreadSafe<T> {
-    inner: Arc<Mutex<ThreadUnsafe<T>>>,
+    inner: Arc<RwLock<ThreadUnsafe<T>>>,
 }
 
+#[cfg(feature = "sync")]
 impl<T> ThreadSafe<T> {
     pub fn new() -> Self {
         Self {
-            inner: Arc::new(Mutex::new(ThreadUnsafe::new())),
+            inner: Arc::new(RwLock::new(ThreadUnsafe::new())),
+        }
+    }
+
+    /// Build a holder that retains up to `n` recent ticks per symbol. See
+    /// [`ThreadUnsafe::with_history`].
+    pub fn with_history(n: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ThreadUnsafe::with_history(n))),
+        }
+    }
+
+    /// Attach a parser for [`put_price_str`](PriceHolder::put_price_str). See
+    /// [`ThreadUnsafe::with_converter`].
+    pub fn with_converter<F>(converter: F) -> Self
+    where
+        F: Fn(&str) -> Result<T, ConversionError> + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(RwLock::new(ThreadUnsafe::new().with_converter(converter))),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> ThreadSafe<T>
+where
+    T: FromStr + 'static,
+{
+    /// Build a holder that parses raw feed values via `T`'s own [`FromStr`].
+    /// See [`ThreadUnsafe::parsing`].
+    pub fn parsing() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ThreadUnsafe::parsing())),
         }
     }
 }
 
+#[cfg(feature = "sync")]
 impl<T> Default for ThreadSafe<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "sync")]
 impl<T> PriceHolder<T> for ThreadSafe<T>
 where
-    T: Unsigned + Copy,
+    T: Copy + Send,
 {
     fn put_price(&mut self, symbol: String, value: T) -> Result<(), SendError<T>> {
-        self.inner.lock().unwrap().put_price(symbol, value)
+        self.inner.write().unwrap().put_price(symbol, value)
     }
 
     fn get_price(&self, symbol: String) -> Option<T> {
-        self.inner.lock().unwrap().get_price(symbol)
+        // Reads never mutate, so they can run concurrently under a read lock.
+        self.inner.read().unwrap().get_price(symbol)
     }
 
     fn next_price(&mut self, symbol: String) -> Result<T, RecvError> {
-        let rx = { self.inner.lock().unwrap().price_receiver(symbol) }; // unlock mutex
+        self.next_price_where(symbol, |_| true)
+    }
+
+    fn next_price_where(
+        &mut self,
+        symbol: String,
+        pred: impl Fn(T) -> bool + Send + Sync + 'static,
+    ) -> Result<T, RecvError> {
+        let rx = { self.inner.write().unwrap().price_receiver(symbol, pred) }; // release lock
         rx.recv()
     }
+
+    fn subscribe(&mut self, symbol: String) -> Receiver<T> {
+        self.inner.write().unwrap().subscribe(symbol)
+    }
+
+    fn history(&self, symbol: String) -> Vec<(Instant, T)> {
+        self.inner.read().unwrap().history(symbol)
+    }
+
+    fn last_n(&self, symbol: String, n: usize) -> Vec<(Instant, T)> {
+        self.inner.read().unwrap().last_n(symbol, n)
+    }
+
+    fn get_price_at(&self, symbol: String, age: Duration) -> Option<(Instant, T)> {
+        self.inner.read().unwrap().get_price_at(symbol, age)
+    }
+
+    fn put_price_str(&mut self, symbol: String, raw: &str) -> Result<(), IngestError<T>> {
+        self.inner.write().unwrap().put_price_str(symbol, raw)
+    }
 }
 
+#[cfg(feature = "sync")]
+type Waiter<T> = (
+    SyncSender<T>,
+    Box<dyn Fn(T) -> bool + Send + Sync>,
+    Weak<()>,
+);
+
+#[cfg(feature = "sync")]
 struct Price<T> {
     value: Option<T>,
-    waiters: Option<Vec<SyncSender<T>>>,
+    waiters: Vec<Waiter<T>>,
+    subscribers: Vec<Sender<T>>,
+    history: VecDeque<(Instant, T)>,
+    history_capacity: usize,
 }
 
+#[cfg(feature = "sync")]
 impl<T> Price<T>
 where
-    T: Unsigned + Copy,
+    T: Copy + Send,
 {
-    fn new() -> Self {
+    fn new(history_capacity: usize) -> Self {
         Self {
             value: None,
-            waiters: None,
+            waiters: Vec::new(),
+            subscribers: Vec::new(),
+            history: VecDeque::new(),
+            history_capacity,
         }
     }
 
-    fn from(value: T) -> Self {
-        Self {
-            value: Some(value),
-            waiters: None,
-        }
+    fn from(value: T, history_capacity: usize) -> Self {
+        let mut price = Self::new(history_capacity);
+        price.update_price(value).ok();
+        price
     }
 
-    fn add_waiter(&mut self, waiter: SyncSender<T>) {
-        match &mut self.waiters {
-            Some(waiters) => waiters.push(waiter),
-            None => self.waiters = Some(vec![waiter]),
-        }
+    fn add_waiter(
+        &mut self,
+        waiter: SyncSender<T>,
+        pred: impl Fn(T) -> bool + Send + Sync + 'static,
+        alive: Weak<()>,
+    ) {
+        self.waiters.push((waiter, Box::new(pred), alive));
+    }
+
+    fn add_subscriber(&mut self, subscriber: Sender<T>) {
+        self.subscribers.push(subscriber);
     }
 
     fn update_price(&mut self, value: T) -> Result<(), SendError<T>> {
         self.value = Some(value);
+        self.record_history(value);
+        self.notify_subscribers(value);
         self.notify_waiters(value)
     }
 
+    fn record_history(&mut self, value: T) {
+        // Opt-in: with a capacity of 0 the zero-history path does no work.
+        if self.history_capacity == 0 {
+            return;
+        }
+        if self.history.len() == self.history_capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back((Instant::now(), value));
+    }
+
     fn notify_waiters(&mut self, value: T) -> Result<(), SendError<T>> {
-        if let Some(waiters) = &self.waiters {
-            for waiter in waiters {
-                waiter.send(value)?;
+        let mut result = Ok(());
+        // Drop waiters whose receiver has hung up regardless of their
+        // predicate; wake and remove the live ones whose predicate matches;
+        // retain the rest for future updates.
+        self.waiters.retain(|(waiter, pred, alive)| {
+            if alive.strong_count() == 0 {
+                return false;
             }
-            self.waiters = None;
-        }
-        Ok(())
+            if !pred(value) {
+                return true;
+            }
+            if let Err(e) = waiter.send(value) {
+                result = Err(e);
+            }
+            false
+        });
+        result
+    }
+
+    fn notify_subscribers(&mut self, value: T) {
+        // Retain only the subscribers whose receiver is still alive; a
+        // `SendError` means the receiver has hung up, so drop that sender.
+        self.subscribers
+            .retain(|subscriber| subscriber.send(value).is_ok());
     }
 }
 
+#[cfg(feature = "sync")]
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -301,9 +641,9 @@ mod tests {
     fn test_thread_unsafe_channel_closed() {
         let mut ph: ThreadUnsafe<u64> = ThreadUnsafe::new();
 
-        let rx = ph.price_receiver("symbol".to_string());
+        let rx = ph.price_receiver("symbol".to_string(), |_| true);
 
-        ph.hashmap.get_mut("symbol").unwrap().waiters = None;
+        ph.hashmap.get_mut("symbol").unwrap().waiters.clear();
         assert_eq!(rx.recv().unwrap_err(), RecvError);
     }
 
@@ -316,15 +656,89 @@ mod tests {
             thread::spawn(move || {
                 thread::sleep(Duration::from_millis(100));
                 ph.inner
-                    .lock()
+                    .write()
                     .unwrap()
                     .hashmap
                     .get_mut("symbol")
                     .unwrap()
-                    .waiters = None;
+                    .waiters
+                    .clear();
             });
         }
 
         assert_eq!(ph.next_price("symbol".to_string()).unwrap_err(), RecvError)
     }
+
+    #[test]
+    fn next_price_where_skips_non_matching_updates() {
+        let mut ph = ThreadSafe::new();
+
+        ph.put_price("symbol".to_string(), 1u64).unwrap();
+
+        {
+            let mut ph = ph.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                ph.put_price("symbol".to_string(), 2).unwrap();
+                ph.put_price("symbol".to_string(), 5).unwrap();
+            })
+        };
+
+        let price = ph
+            .next_price_where("symbol".to_string(), |p| p >= 5)
+            .unwrap();
+        assert_eq!(price, 5);
+    }
+
+    #[test]
+    fn history_is_bounded_and_ordered() {
+        let mut ph = ThreadUnsafe::with_history(3);
+
+        for p in 1u64..=5 {
+            ph.put_price("symbol".to_string(), p).unwrap();
+        }
+
+        let values: Vec<_> = ph
+            .history("symbol".to_string())
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect();
+        assert_eq!(values, vec![3, 4, 5]);
+
+        let last_two: Vec<_> = ph
+            .last_n("symbol".to_string(), 2)
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect();
+        assert_eq!(last_two, vec![4, 5]);
+    }
+
+    #[test]
+    fn no_history_by_default() {
+        let mut ph = ThreadUnsafe::new();
+        ph.put_price("symbol".to_string(), 1u64).unwrap();
+        assert!(ph.history("symbol".to_string()).is_empty());
+    }
+
+    #[test]
+    fn put_price_str_parses_decimal_feed() {
+        let mut ph: ThreadUnsafe<f64> = ThreadUnsafe::parsing();
+
+        ph.put_price_str("symbol".to_string(), "3.25").unwrap();
+        assert_eq!(ph.get_price("symbol".to_string()).unwrap(), 3.25);
+
+        assert!(ph.put_price_str("symbol".to_string(), "not_a_price").is_err());
+    }
+
+    #[test]
+    fn dropped_waiter_is_pruned_even_if_predicate_never_matches() {
+        let mut ph: ThreadUnsafe<u64> = ThreadUnsafe::new();
+
+        let rx = ph.price_receiver("symbol".to_string(), |_| false);
+        drop(rx);
+
+        // The non-matching update must still evict the dead waiter.
+        ph.put_price("symbol".to_string(), 1).unwrap();
+        assert!(ph.hashmap.get("symbol").unwrap().waiters.is_empty());
+    }
 }
\ No newline at end of file